@@ -1,25 +1,67 @@
+use std::fs;
 use std::io::{Error, ErrorKind};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use clap::Parser;
-use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer};
+use clap::{Parser, Subcommand};
+use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer, Pixel, Primitive, Rgb32FImage};
 use image::io::Reader as ImageReader;
+use num_traits::NumCast;
+use rayon::prelude::*;
 
 #[derive(clap::ValueEnum, Clone, Default)]
 enum Blend {
+    /// Single-background fallback: only use the white-background color
     White,
-    #[default]
+    /// Single-background fallback: only use the black-background color
     Black,
+    /// Combine both backgrounds into a least-squares foreground estimate (recommended)
+    #[default]
     Mix,
 }
 
+#[derive(clap::ValueEnum, Clone, Default)]
+enum Colorspace {
+    #[default]
+    Srgb,
+    Linear,
+}
+
+#[derive(clap::ValueEnum, Clone, Default)]
+enum AlphaEstimate {
+    /// Average the per-channel (R, G, B) alpha estimates
+    #[default]
+    Mean,
+    /// Take the median of the per-channel alpha estimates, rejecting a single noisy channel
+    Median,
+}
+
 #[derive(Parser)]
 #[clap(version, about="Derives an image with alpha channel from two alpha-less images")]
 #[command(version, about)]
-struct Args {
-    #[clap(short, long, value_enum, help="Which image to take the color values from (mix is experimental)", default_value_t=Blend::default())]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Recover a single black/white pair
+    Single(SingleArgs),
+    /// Recover every black/white pair found in a directory
+    Batch(BatchArgs),
+}
+
+#[derive(clap::Args)]
+struct SingleArgs {
+    #[clap(short, long, value_enum, help="Which image to take the color values from", default_value_t=Blend::default())]
     blend: Blend,
+    #[clap(short, long, value_enum, help="Color space the input images are encoded in; the recovery math itself always runs in linear light", default_value_t=Colorspace::default())]
+    colorspace: Colorspace,
+    #[clap(short, long, value_enum, help="How to combine the per-channel (R, G, B) alpha estimates into one alpha value", default_value_t=AlphaEstimate::default())]
+    alpha_estimate: AlphaEstimate,
+    #[clap(short, long, help="Number of threads to use for the per-pixel recovery (1 = serial, 0 = automatic)", default_value_t=0)]
+    threads: usize,
 
     #[clap(help="An image with a solid black background")]
     black: PathBuf,
@@ -29,8 +71,71 @@ struct Args {
     out: PathBuf,
 }
 
+#[derive(clap::Args)]
+struct BatchArgs {
+    #[clap(short, long, value_enum, help="Which image to take the color values from", default_value_t=Blend::default())]
+    blend: Blend,
+    #[clap(short, long, value_enum, help="Color space the input images are encoded in; the recovery math itself always runs in linear light", default_value_t=Colorspace::default())]
+    colorspace: Colorspace,
+    #[clap(short, long, value_enum, help="How to combine the per-channel (R, G, B) alpha estimates into one alpha value", default_value_t=AlphaEstimate::default())]
+    alpha_estimate: AlphaEstimate,
+    #[clap(short, long, help="Number of threads to use for the per-pixel recovery of each pair (1 = serial, 0 = automatic)", default_value_t=0)]
+    threads: usize,
+
+    #[clap(help="Directory to search for black/white image pairs")]
+    input_dir: PathBuf,
+    #[clap(help="Directory the recovered images are written into")]
+    output_dir: PathBuf,
+
+    #[clap(long, help="Filename suffix (before the extension) that marks the black-background image of a pair", default_value="_black")]
+    black_suffix: String,
+    #[clap(long, help="Filename suffix (before the extension) that marks the white-background image of a pair", default_value="_white")]
+    white_suffix: String,
+}
+
+/// A discovered `{base}{black_suffix}.ext` / `{base}{white_suffix}.ext` pair, named after
+/// its shared `base`. The recovered output is written to `{base}.png` (or `.exr` for
+/// 32-bit float input, see [`batch_output_path`]) in the output directory.
+struct Pair {
+    base: String,
+    black: PathBuf,
+    white: PathBuf,
+}
+
+/// Scans `dir` for files ending in `black_suffix` and pairs each one up with the sibling
+/// file that shares its base name but ends in `white_suffix`. Files with no matching
+/// sibling are reported via `warn` and left out of the returned list rather than
+/// aborting the scan.
+fn discover_pairs(dir: &Path, black_suffix: &str, white_suffix: &str, warn: impl Fn(&str)) -> Result<Vec<Pair>, Error> {
+    let mut pairs = Vec::new();
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else { continue };
+        let Some(base) = stem.strip_suffix(black_suffix) else { continue };
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else { continue };
+
+        let white_path = path.with_file_name(format!("{base}{white_suffix}.{extension}"));
+        if !white_path.is_file() {
+            warn(&format!("No matching white-background image for '{}', skipping", path.display()));
+            continue;
+        }
+
+        pairs.push(Pair {
+            base: base.to_string(),
+            black: path,
+            white: white_path,
+        });
+    }
+
+    Ok(pairs)
+}
+
 fn preflight_checks(black: &DynamicImage, white: &DynamicImage) -> Result<(), Error> {
-    let unsupported_color_types = vec![ColorType::Rgb32F, ColorType::Rgba32F];
     let black_color = black.color();
     let white_color = white.color();
 
@@ -41,13 +146,6 @@ fn preflight_checks(black: &DynamicImage, white: &DynamicImage) -> Result<(), Er
         ));
     }
 
-    if unsupported_color_types.contains(&black_color) || unsupported_color_types.contains(&white_color) {
-        return Err(Error::new(
-            ErrorKind::InvalidInput,
-            "32-bit color is not supported",
-        ));
-    }
-
     if black_color != white_color {
         return Err(Error::new(
             ErrorKind::InvalidInput,
@@ -58,12 +156,29 @@ fn preflight_checks(black: &DynamicImage, white: &DynamicImage) -> Result<(), Er
     Ok(())
 }
 
+/// Combines the per-channel (R, G, B) alpha estimates from [`magic`] into a single
+/// alpha value, either by averaging them or by taking the median to reject one
+/// noisy channel.
+fn combine_alpha(channel_alphas: [f64; 3], alpha_estimate: &AlphaEstimate) -> f64 {
+    match alpha_estimate {
+        AlphaEstimate::Mean => (channel_alphas[0] + channel_alphas[1] + channel_alphas[2]) / 3.0,
+        AlphaEstimate::Median => {
+            // total_cmp orders NaN/Inf deterministically instead of panicking, so an
+            // out-of-range float channel (admitted since 32-bit float input is allowed)
+            // can't take down the recovery.
+            let mut sorted = channel_alphas;
+            sorted.sort_by(f64::total_cmp);
+            sorted[1]
+        }
+    }
+}
+
 /// Does Math™ on two input pixels from images with black and white backgrounds
 /// respectively to obtain a "fixed" pixel that includes an alpha channel.
 /// The input pixels are expected to be three-item f32 arrays,
 /// the output pixel is a four-item f64 array.
 /// Based on the method explained here: https://www.interact-sw.co.uk/iangblog/2007/01/30/recoveralpha
-fn magic(black_pixel: [f32; 3], white_pixel: [f32; 3], blend: &Blend) -> [f64; 4] {
+fn magic(black_pixel: [f32; 3], white_pixel: [f32; 3], blend: &Blend, alpha_estimate: &AlphaEstimate) -> [f64; 4] {
     let (rb, gb, bb, rw, gw, bw) = (
         black_pixel[0] as f64,
         black_pixel[1] as f64,
@@ -73,10 +188,12 @@ fn magic(black_pixel: [f32; 3], white_pixel: [f32; 3], blend: &Blend) -> [f64; 4
         white_pixel[2] as f64,
     );
 
-    let (alpha, mut rs, mut gs, mut bs) = (
-        rb - rw + 1.0, // this can occasionally exceed 1.0 but it seems saving as non-32-bit automatically clips this to [0.0, 1.0]
-        0.0, 0.0, 0.0
-    );
+    // per-channel alpha estimate; this can occasionally exceed 1.0. Non-32-bit outputs
+    // clip the final result to [0.0, 1.0], 32-bit outputs keep it as-is
+    let channel_alphas = [rb - rw + 1.0, gb - gw + 1.0, bb - bw + 1.0];
+    let alpha = combine_alpha(channel_alphas, alpha_estimate);
+
+    let (mut rs, mut gs, mut bs) = (0.0, 0.0, 0.0);
 
     if alpha > 0.0 {
         match blend {
@@ -91,119 +208,360 @@ fn magic(black_pixel: [f32; 3], white_pixel: [f32; 3], blend: &Blend) -> [f64; 4
                 bs = bb / alpha;
             }
             Blend::Mix => {
-                // not actually all that accurate, just in here as an experiment
-                rs = (rb + rw) / 2.0 / alpha;
-                gs = (gb + gw) / 2.0 / alpha;
-                bs = (bb + bw) / 2.0 / alpha;
+                // average the two independent foreground estimates implied by each background
+                rs = (rb / alpha + (rw - (1.0 - alpha)) / alpha) / 2.0;
+                gs = (gb / alpha + (gw - (1.0 - alpha)) / alpha) / 2.0;
+                bs = (bb / alpha + (bw - (1.0 - alpha)) / alpha) / 2.0;
             }
         }
     }
 
-    return [rs, gs, bs, alpha];
+    [rs, gs, bs, alpha]
+}
+
+#[cfg(test)]
+mod alpha_tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "expected {a} to be close to {b}");
+    }
+
+    #[test]
+    fn combine_alpha_mean_averages_all_channels() {
+        assert_close(combine_alpha([0.2, 0.4, 0.6], &AlphaEstimate::Mean), 0.4);
+    }
+
+    #[test]
+    fn combine_alpha_median_rejects_a_noisy_channel() {
+        assert_close(combine_alpha([0.2, 0.21, 0.9], &AlphaEstimate::Median), 0.21);
+    }
+
+    #[test]
+    fn combine_alpha_median_does_not_panic_on_a_nan_channel() {
+        // reachable once 32-bit float input is admitted: an out-of-range HDR channel
+        // can legitimately produce a NaN/Inf alpha estimate that must sort, not panic
+        combine_alpha([0.2, f64::NAN, 0.9], &AlphaEstimate::Median);
+    }
+
+    #[test]
+    fn magic_mix_recovers_a_known_foreground() {
+        let fg = [0.7_f64, 0.3, 0.9];
+        let alpha = 0.6_f64;
+
+        // composite = fg·alpha + bg·(1-alpha); bg is 0 (black) or 1 (white)
+        let black_pixel = [
+            (fg[0] * alpha) as f32,
+            (fg[1] * alpha) as f32,
+            (fg[2] * alpha) as f32,
+        ];
+        let white_pixel = [
+            (fg[0] * alpha + (1.0 - alpha)) as f32,
+            (fg[1] * alpha + (1.0 - alpha)) as f32,
+            (fg[2] * alpha + (1.0 - alpha)) as f32,
+        ];
+
+        let recovered = magic(black_pixel, white_pixel, &Blend::Mix, &AlphaEstimate::Mean);
+
+        assert_close(recovered[3], alpha);
+        assert_close(recovered[0], fg[0]);
+        assert_close(recovered[1], fg[1]);
+        assert_close(recovered[2], fg[2]);
+    }
+}
+
+/// Converts a single sRGB-encoded channel value to linear light.
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a single linear-light channel value back to sRGB encoding.
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decodes an input pixel's color channels to linear light ahead of the
+/// recovery math in [`magic`]. A no-op when `colorspace` is already linear.
+fn decode_pixel(pixel: [f32; 3], colorspace: &Colorspace) -> [f32; 3] {
+    match colorspace {
+        Colorspace::Srgb => [
+            srgb_to_linear(pixel[0] as f64) as f32,
+            srgb_to_linear(pixel[1] as f64) as f32,
+            srgb_to_linear(pixel[2] as f64) as f32,
+        ],
+        Colorspace::Linear => pixel,
+    }
+}
+
+/// Re-encodes a recovered straight color from linear light back to the output
+/// color space. Alpha is a coverage value, not a color, so it is never touched here.
+fn encode_color(color: [f64; 3], colorspace: &Colorspace) -> [f64; 3] {
+    match colorspace {
+        Colorspace::Srgb => [
+            linear_to_srgb(color[0]),
+            linear_to_srgb(color[1]),
+            linear_to_srgb(color[2]),
+        ],
+        Colorspace::Linear => color,
+    }
+}
+
+#[cfg(test)]
+mod colorspace_tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-6, "expected {a} to be close to {b}");
+    }
+
+    #[test]
+    fn endpoints_round_trip_exactly() {
+        assert_close(srgb_to_linear(0.0), 0.0);
+        assert_close(srgb_to_linear(1.0), 1.0);
+        assert_close(linear_to_srgb(0.0), 0.0);
+        assert_close(linear_to_srgb(1.0), 1.0);
+    }
+
+    #[test]
+    fn srgb_to_linear_is_continuous_at_its_breakpoint() {
+        let just_below = srgb_to_linear(0.04045 - 1e-7);
+        let just_above = srgb_to_linear(0.04045 + 1e-7);
+        assert!((just_below - just_above).abs() < 1e-6);
+    }
+
+    #[test]
+    fn linear_to_srgb_is_continuous_at_its_breakpoint() {
+        // the canonical sRGB constants don't line up to the last digit, so the
+        // two branches meet with a small (sub-1e-5) jump rather than bit-exact continuity
+        let just_below = linear_to_srgb(0.0031308 - 1e-7);
+        let just_above = linear_to_srgb(0.0031308 + 1e-7);
+        assert!((just_below - just_above).abs() < 1e-5);
+    }
+
+    #[test]
+    fn srgb_linear_round_trip() {
+        for &c in &[0.0, 0.01, 0.04045, 0.1, 0.5, 0.73, 1.0] {
+            assert_close(linear_to_srgb(srgb_to_linear(c)), c);
+        }
+    }
 }
 
 const SCALAR8: f64 = 255.0;
 const SCALAR16: f64 = 65535.0;
+const SCALAR32F: f64 = 1.0;
 
-fn main() -> Result<(), Error> {
-    let args = Args::parse();
+/// Casts a recovered channel value into an output subpixel. Integer subpixels
+/// (`u8`/`u16`) get clamped to their range by the numeric cast; float subpixels
+/// pass the unclamped value straight through.
+fn cast_sample<S: Primitive>(value: f64) -> S {
+    NumCast::from(value).unwrap_or(if value < 0.0 { S::DEFAULT_MIN_VALUE } else { S::DEFAULT_MAX_VALUE })
+}
 
-    // println!("black path: {}", args.black.display());
-    // println!("white path: {}", args.white.display());
-    // println!("out path: {}", args.out.display());
+/// Generic per-pixel recovery, parameterized over the output pixel type so the
+/// `L8`/`L16`/`Rgb8`/`Rgb16`/`Rgb32F` cases in `main` no longer need one hand-written
+/// loop each. `P`'s channel count decides whether a `LumaA` or `Rgba` pixel is built;
+/// `scale` is the integer/float scalar (`SCALAR8`/`SCALAR16`/`SCALAR32F`) for the detected `ColorType`.
+fn recover<P>(black: &Rgb32FImage, white: &Rgb32FImage, blend: &Blend, colorspace: &Colorspace, alpha_estimate: &AlphaEstimate, scale: f64, pool: &rayon::ThreadPool) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel + Send,
+    P::Subpixel: Send,
+{
+    let (w, h) = black.dimensions();
+    let mut out = ImageBuffer::<P, Vec<P::Subpixel>>::new(w, h);
+    let channels = P::CHANNEL_COUNT as usize;
+    let width = w as usize;
+
+    pool.install(|| {
+        out.as_mut().par_chunks_mut(width * channels).enumerate().for_each(|(y, row)| {
+            for x in 0..width {
+                let bp = decode_pixel(black.get_pixel(x as u32, y as u32).0, colorspace);
+                let wp = decode_pixel(white.get_pixel(x as u32, y as u32).0, colorspace);
+                let new = magic(bp, wp, blend, alpha_estimate);
+                let color = encode_color([new[0], new[1], new[2]], colorspace);
+
+                let pixel = &mut row[x * channels..x * channels + channels];
+                if channels == 2 {
+                    // grayscale output: the recovered red channel stands in for luma
+                    pixel[0] = cast_sample(color[0] * scale);
+                    pixel[1] = cast_sample(new[3] * scale);
+                } else {
+                    pixel[0] = cast_sample(color[0] * scale);
+                    pixel[1] = cast_sample(color[1] * scale);
+                    pixel[2] = cast_sample(color[2] * scale);
+                    pixel[3] = cast_sample(new[3] * scale);
+                }
+            }
+        });
+    });
 
-    println!("Loading images…");
+    out
+}
 
-    let start = Instant::now();
+/// Saves a 32-bit float `Rgba` buffer without clamping channel values to [0.0, 1.0].
+/// The output extension decides the codec: `.exr` keeps the alpha channel, `.hdr`
+/// (Radiance) has no alpha channel of its own so it is dropped on the way out.
+fn save_float_image(image: &ImageBuffer<image::Rgba<f32>, Vec<f32>>, out: &Path) -> Result<(), Error> {
+    let extension = out
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("exr") => image.save(out).map_err(|err| Error::other(err.to_string())),
+        Some("hdr") => {
+            let rgb_image: ImageBuffer<image::Rgb<f32>, Vec<f32>> = ImageBuffer::from_fn(
+                image.width(),
+                image.height(),
+                |x, y| {
+                    let px = image.get_pixel(x, y).0;
+                    image::Rgb([px[0], px[1], px[2]])
+                },
+            );
+
+            rgb_image.save(out).map_err(|err| Error::other(err.to_string()))
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "32-bit float output requires a .exr or .hdr extension",
+        )),
+    }
+}
 
-    let black_reader = ImageReader::open(args.black).expect("Can't open file");
-    let white_reader = ImageReader::open(args.white).expect("Can't open file");
+/// Opens and decodes a black/white pair from `black_path`/`white_path`, checking that
+/// they're fit to recover together. Shared by the `single` and `batch` subcommands.
+fn load_pair(black_path: &Path, white_path: &Path) -> Result<(DynamicImage, DynamicImage), Error> {
+    let black_reader = ImageReader::open(black_path).map_err(|err| Error::other(err.to_string()))?;
+    let white_reader = ImageReader::open(white_path).map_err(|err| Error::other(err.to_string()))?;
 
-    let black_image = black_reader.decode().expect("Can't decode image");
-    let white_image = white_reader.decode().expect("Can't decode image");
+    let black_image = black_reader.decode().map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
+    let white_image = white_reader.decode().map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))?;
 
-    preflight_checks(&black_image, &white_image).unwrap();
+    preflight_checks(&black_image, &white_image)?;
+
+    Ok((black_image, white_image))
+}
 
-    let image_dim = black_image.dimensions();
+/// Picks the output path for a recovered batch pair, using an extension that can
+/// actually hold the detected `ColorType`'s value range: `Rgb32F`/`Rgba32F` pairs
+/// need `.exr` to keep their unclamped, out-of-gamut recovered values.
+fn batch_output_path(output_dir: &Path, base: &str, color_type: ColorType) -> PathBuf {
+    let extension = match color_type {
+        ColorType::Rgb32F | ColorType::Rgba32F => "exr",
+        _ => "png",
+    };
+    output_dir.join(format!("{base}.{extension}"))
+}
 
+/// Recovers alpha for an already-loaded black/white pair and writes the result to `out_path`.
+fn recover_pair(black_image: DynamicImage, white_image: DynamicImage, out_path: &Path, blend: &Blend, colorspace: &Colorspace, alpha_estimate: &AlphaEstimate, pool: &rayon::ThreadPool) -> Result<(), Error> {
     let color_type = black_image.color();
     let black_rgb = black_image.into_rgb32f();
     let white_rgb = white_image.into_rgb32f();
 
-    let format_name = if color_type.has_color() { "RGB" } else { "grayscale" };
-    let bits_per_channel = color_type.bits_per_pixel() / color_type.channel_count() as u16;
-    println!("Generating {format_name} output at {}×{} with {bits_per_channel} bits per channel…", image_dim.0, image_dim.1);
-
-    // TODO: please let there be a way to reduce the amount of code in this match block 😭
     match color_type {
         ColorType::L8 | ColorType::La8 => {
-            let mut luma_image = ImageBuffer::new(image_dim.0, image_dim.1);
-            for (x, y, pixel) in luma_image.enumerate_pixels_mut() {
-                let bp = black_rgb.get_pixel(x, y).0;
-                let wp = white_rgb.get_pixel(x, y).0;
-                let new = magic(bp, wp, &args.blend);
-
-                *pixel = image::LumaA([
-                    (new[0] * SCALAR8) as u8,
-                    (new[3] * SCALAR8) as u8,
-                ]);
-            }
-
-            luma_image.save(args.out.as_path()).unwrap();
+            let luma_image = recover::<image::LumaA<u8>>(&black_rgb, &white_rgb, blend, colorspace, alpha_estimate, SCALAR8, pool);
+            luma_image.save(out_path).map_err(|err| Error::other(err.to_string()))?;
         }
         ColorType::L16 | ColorType::La16 => {
-            let mut luma_image = ImageBuffer::new(image_dim.0, image_dim.1);
-            for (x, y, pixel) in luma_image.enumerate_pixels_mut() {
-                let bp = black_rgb.get_pixel(x, y).0;
-                let wp = white_rgb.get_pixel(x, y).0;
-                let new = magic(bp, wp, &args.blend);
-
-                *pixel = image::LumaA([
-                    (new[0] * SCALAR16) as u16,
-                    (new[3] * SCALAR16) as u16,
-                ]);
-            }
-
-            luma_image.save(args.out.as_path()).unwrap();
+            let luma_image = recover::<image::LumaA<u16>>(&black_rgb, &white_rgb, blend, colorspace, alpha_estimate, SCALAR16, pool);
+            luma_image.save(out_path).map_err(|err| Error::other(err.to_string()))?;
         }
         ColorType::Rgb8 | ColorType::Rgba8 => {
-            let mut rgb_image = ImageBuffer::new(image_dim.0, image_dim.1);
-            for (x, y, pixel) in rgb_image.enumerate_pixels_mut() {
-                let bp = black_rgb.get_pixel(x, y).0;
-                let wp = white_rgb.get_pixel(x, y).0;
-                let new = magic(bp, wp, &args.blend);
-
-                *pixel = image::Rgba([
-                    (new[0] * SCALAR8) as u8,
-                    (new[1] * SCALAR8) as u8,
-                    (new[2] * SCALAR8) as u8,
-                    (new[3] * SCALAR8) as u8,
-                ]);
-            }
-
-            rgb_image.save(args.out.as_path()).unwrap();
+            let rgb_image = recover::<image::Rgba<u8>>(&black_rgb, &white_rgb, blend, colorspace, alpha_estimate, SCALAR8, pool);
+            rgb_image.save(out_path).map_err(|err| Error::other(err.to_string()))?;
         }
         ColorType::Rgb16 | ColorType::Rgba16 => {
-            let mut rgb_image = ImageBuffer::new(image_dim.0, image_dim.1);
-            for (x, y, pixel) in rgb_image.enumerate_pixels_mut() {
-                let bp = black_rgb.get_pixel(x, y).0;
-                let wp = white_rgb.get_pixel(x, y).0;
-                let new = magic(bp, wp, &args.blend);
-
-                *pixel = image::Rgba([
-                    (new[0] * SCALAR16) as u16,
-                    (new[1] * SCALAR16) as u16,
-                    (new[2] * SCALAR16) as u16,
-                    (new[3] * SCALAR16) as u16,
-                ]);
+            let rgb_image = recover::<image::Rgba<u16>>(&black_rgb, &white_rgb, blend, colorspace, alpha_estimate, SCALAR16, pool);
+            rgb_image.save(out_path).map_err(|err| Error::other(err.to_string()))?;
+        }
+        ColorType::Rgb32F | ColorType::Rgba32F => {
+            // EXR/HDR sprites are conventionally stored in linear light already; running
+            // the sRGB decode on top of that would warp the recovered alpha and color.
+            if matches!(colorspace, Colorspace::Srgb) {
+                eprintln!("Warning: 32-bit float input is assumed to already be linear; ignoring --colorspace srgb");
             }
-
-            rgb_image.save(args.out.as_path()).unwrap();
+            let float_image = recover::<image::Rgba<f32>>(&black_rgb, &white_rgb, blend, &Colorspace::Linear, alpha_estimate, SCALAR32F, pool);
+            save_float_image(&float_image, out_path)?;
         }
         _ => {}
     }
 
+    Ok(())
+}
+
+fn run_single(args: SingleArgs) -> Result<(), Error> {
+    println!("Loading images…");
+
+    let start = Instant::now();
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build()
+        .expect("Can't build thread pool");
+
+    let (black_image, white_image) = load_pair(&args.black, &args.white)?;
+    recover_pair(black_image, white_image, &args.out, &args.blend, &args.colorspace, &args.alpha_estimate, &pool)?;
+
     println!("{} saved in {:.02}s!", args.out.file_name().unwrap().to_str().unwrap(), start.elapsed().as_secs_f64());
 
     Ok(())
 }
+
+fn run_batch(args: BatchArgs) -> Result<(), Error> {
+    let pairs = discover_pairs(&args.input_dir, &args.black_suffix, &args.white_suffix, |msg| eprintln!("Warning: {msg}"))?;
+
+    if pairs.is_empty() {
+        println!("No black/white pairs found in {}", args.input_dir.display());
+        return Ok(());
+    }
+
+    fs::create_dir_all(&args.output_dir)?;
+
+    println!("Recovering {} pair(s)…", pairs.len());
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads)
+        .build()
+        .expect("Can't build thread pool");
+
+    let start = Instant::now();
+
+    pool.install(|| {
+        pairs.par_iter().for_each(|pair| {
+            let pair_start = Instant::now();
+
+            let outcome = load_pair(&pair.black, &pair.white).and_then(|(black_image, white_image)| {
+                let out_path = batch_output_path(&args.output_dir, &pair.base, black_image.color());
+                recover_pair(black_image, white_image, &out_path, &args.blend, &args.colorspace, &args.alpha_estimate, &pool)?;
+                Ok(out_path)
+            });
+
+            match outcome {
+                Ok(out_path) => println!("{} saved in {:.02}s", out_path.display(), pair_start.elapsed().as_secs_f64()),
+                Err(err) => eprintln!("Warning: skipping '{}': {err}", pair.base),
+            }
+        });
+    });
+
+    println!("Recovered {} pair(s) in {:.02}s total", pairs.len(), start.elapsed().as_secs_f64());
+
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Single(args) => run_single(args),
+        Command::Batch(args) => run_batch(args),
+    }
+}